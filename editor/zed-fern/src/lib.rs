@@ -1,9 +1,120 @@
+use std::fs;
+use zed_extension_api::lsp::{Completion, CompletionKind, Symbol, SymbolKind};
+use zed_extension_api::settings::LspSettings;
+use zed_extension_api::{CodeLabel, CodeLabelSpan};
 use zed_extension_api as zed;
 
+/// A language server this extension provides, paired with the host language
+/// whose fern regions it analyzes. Each entry scopes one such attachment; the
+/// table holds a single entry, for plain fern files, and lists no other host
+/// file types.
+struct FernServer {
+    id: &'static str,
+    language: &'static str,
+}
+
+const FERN_SERVERS: &[FernServer] = &[FernServer {
+    id: "fern-lsp",
+    language: "fern",
+}];
+
+fn fern_server(language_server_id: &zed::LanguageServerId) -> Option<&'static FernServer> {
+    FERN_SERVERS
+        .iter()
+        .find(|server| server.id == language_server_id.as_ref())
+}
+
 struct FernExtension {
     cached_binary_path: Option<String>,
 }
 
+impl FernExtension {
+    fn language_server_binary_path(
+        &mut self,
+        language_server_id: &zed::LanguageServerId,
+        worktree: &zed::Worktree,
+    ) -> zed::Result<String> {
+        if let Some(path) = worktree.which("fern") {
+            return Ok(path);
+        }
+
+        if let Some(path) = &self.cached_binary_path {
+            if fs::metadata(path).map_or(false, |stat| stat.is_file()) {
+                return Ok(path.clone());
+            }
+        }
+
+        zed::set_language_server_installation_status(
+            language_server_id,
+            &zed::LanguageServerInstallationStatus::CheckingForUpdate,
+        );
+        let release = zed::latest_github_release(
+            "NijanthanR/fern",
+            zed::GithubReleaseOptions {
+                require_assets: true,
+                pre_release: false,
+            },
+        )?;
+
+        let (os, arch) = zed::current_platform();
+        let (os_name, ext, file_type) = match os {
+            zed::Os::Mac => ("macos", "tar.gz", zed::DownloadedFileType::GzipTar),
+            zed::Os::Linux => ("linux", "tar.gz", zed::DownloadedFileType::GzipTar),
+            zed::Os::Windows => ("windows", "zip", zed::DownloadedFileType::Zip),
+        };
+        let arch_name = match arch {
+            zed::Architecture::Aarch64 => "aarch64",
+            zed::Architecture::X8664 => "x86_64",
+            zed::Architecture::X86 => "x86",
+        };
+        let asset_name = format!("fern-{arch_name}-{os_name}.{ext}");
+
+        let asset = release
+            .assets
+            .iter()
+            .find(|asset| asset.name == asset_name)
+            .ok_or_else(|| format!("no asset found matching {asset_name:?}"))?;
+
+        let version_dir = format!("fern-{}", release.version);
+        let binary_name = if os == zed::Os::Windows {
+            "fern.exe"
+        } else {
+            "fern"
+        };
+        let binary_path = format!("{version_dir}/{binary_name}");
+
+        if !fs::metadata(&binary_path).map_or(false, |stat| stat.is_file()) {
+            zed::set_language_server_installation_status(
+                language_server_id,
+                &zed::LanguageServerInstallationStatus::Downloading,
+            );
+
+            zed::download_file(&asset.download_url, &version_dir, file_type).map_err(|err| {
+                zed::set_language_server_installation_status(
+                    language_server_id,
+                    &zed::LanguageServerInstallationStatus::Failed(err.to_string()),
+                );
+                format!("failed to download fern: {err}")
+            })?;
+
+            zed::make_file_executable(&binary_path)?;
+
+            // Keep only the version we just installed.
+            let entries = fs::read_dir(".")
+                .map_err(|err| format!("failed to list working directory {err}"))?;
+            for entry in entries {
+                let entry = entry.map_err(|err| format!("failed to load directory entry {err}"))?;
+                if entry.file_name().to_str() != Some(&version_dir) {
+                    fs::remove_dir_all(entry.path()).ok();
+                }
+            }
+        }
+
+        self.cached_binary_path = Some(binary_path.clone());
+        Ok(binary_path)
+    }
+}
+
 impl zed::Extension for FernExtension {
     fn new() -> Self {
         FernExtension {
@@ -16,22 +127,133 @@ impl zed::Extension for FernExtension {
         language_server_id: &zed::LanguageServerId,
         worktree: &zed::Worktree,
     ) -> zed::Result<zed::Command> {
-        // Only handle fern-lsp
-        if language_server_id.as_ref() != "fern-lsp" {
-            return Err("Unknown language server".into());
+        // Only handle the servers this extension provides.
+        if fern_server(language_server_id).is_none() {
+            return Err(format!("Unknown language server: {}", language_server_id.as_ref()).into());
         }
 
-        // Try to find fern in PATH
-        let fern_path = worktree
-            .which("fern")
-            .ok_or_else(|| "fern not found in PATH. Install fern or add it to your PATH.")?;
+        let binary = LspSettings::for_worktree(language_server_id.as_ref(), worktree)
+            .ok()
+            .and_then(|settings| settings.binary);
+
+        let command = match binary.as_ref().and_then(|binary| binary.path.clone()) {
+            Some(path) => path,
+            None => self.language_server_binary_path(language_server_id, worktree)?,
+        };
+
+        let args = binary
+            .as_ref()
+            .and_then(|binary| binary.arguments.clone())
+            .unwrap_or_else(|| vec!["lsp".to_string()]);
 
-        self.cached_binary_path = Some(fern_path.clone());
+        let env = binary
+            .and_then(|binary| binary.env)
+            .map(|env| env.into_iter().collect())
+            .unwrap_or_default();
 
         Ok(zed::Command {
-            command: fern_path,
-            args: vec!["lsp".to_string()],
-            env: Default::default(),
+            command,
+            args,
+            env,
+        })
+    }
+
+    fn language_server_initialization_options(
+        &mut self,
+        language_server_id: &zed::LanguageServerId,
+        worktree: &zed::Worktree,
+    ) -> zed::Result<Option<zed::serde_json::Value>> {
+        let mut options = LspSettings::for_worktree(language_server_id.as_ref(), worktree)
+            .ok()
+            .and_then(|settings| settings.initialization_options)
+            .unwrap_or_else(|| zed::serde_json::json!({}));
+
+        // Scope the server's analysis to the language it was launched for.
+        if let (Some(server), Some(object)) = (fern_server(language_server_id), options.as_object_mut()) {
+            object
+                .entry("language")
+                .or_insert_with(|| server.language.into());
+        }
+
+        Ok(Some(options))
+    }
+
+    fn language_server_workspace_configuration(
+        &mut self,
+        language_server_id: &zed::LanguageServerId,
+        worktree: &zed::Worktree,
+    ) -> zed::Result<Option<zed::serde_json::Value>> {
+        Ok(LspSettings::for_worktree(language_server_id.as_ref(), worktree)
+            .ok()
+            .and_then(|settings| settings.settings))
+    }
+
+    fn label_for_completion(
+        &self,
+        _language_server_id: &zed::LanguageServerId,
+        completion: Completion,
+    ) -> Option<CodeLabel> {
+        let name = &completion.label;
+
+        // Keywords carry no identifier context, so highlight them literally.
+        if matches!(completion.kind?, CompletionKind::Keyword) {
+            return Some(CodeLabel {
+                spans: vec![CodeLabelSpan::literal(name.clone(), Some("keyword".into()))],
+                filter_range: (0..name.len()).into(),
+                code: name.clone(),
+            });
+        }
+
+        // Wrap the identifier in a tiny synthetic fern snippet so the grammar
+        // highlights it the same way it would in source.
+        let (prefix, suffix) = match completion.kind? {
+            CompletionKind::Function | CompletionKind::Method | CompletionKind::Constructor => {
+                ("fn ", "() {}")
+            }
+            CompletionKind::Variable | CompletionKind::Value => ("let ", " = ()"),
+            CompletionKind::Constant | CompletionKind::EnumMember => ("const ", " = ()"),
+            CompletionKind::Field | CompletionKind::Property => ("", ": ()"),
+            CompletionKind::Class | CompletionKind::Struct => ("struct ", " {}"),
+            CompletionKind::Interface | CompletionKind::Enum => ("enum ", " {}"),
+            CompletionKind::TypeParameter => ("type ", ""),
+            CompletionKind::Module => ("mod ", ""),
+            _ => return None,
+        };
+
+        let code = format!("{prefix}{name}{suffix}");
+        let start = prefix.len();
+        Some(CodeLabel {
+            spans: vec![CodeLabelSpan::code_range(0..code.len())],
+            filter_range: (start..start + name.len()).into(),
+            code,
+        })
+    }
+
+    fn label_for_symbol(
+        &self,
+        _language_server_id: &zed::LanguageServerId,
+        symbol: Symbol,
+    ) -> Option<CodeLabel> {
+        let name = &symbol.name;
+
+        let (prefix, suffix) = match symbol.kind {
+            SymbolKind::Function | SymbolKind::Method | SymbolKind::Constructor => ("fn ", "() {}"),
+            SymbolKind::Variable => ("let ", " = ()"),
+            SymbolKind::Constant => ("const ", " = ()"),
+            SymbolKind::Field | SymbolKind::Property => ("", ": ()"),
+            SymbolKind::Class | SymbolKind::Struct | SymbolKind::Object => ("struct ", " {}"),
+            SymbolKind::Interface | SymbolKind::Enum => ("enum ", " {}"),
+            SymbolKind::TypeParameter => ("type ", ""),
+            SymbolKind::Module | SymbolKind::Namespace | SymbolKind::Package => ("mod ", ""),
+            _ => return None,
+        };
+
+        let code = format!("{prefix}{name}{suffix}");
+        let start = prefix.len();
+        Some(CodeLabel {
+            spans: vec![CodeLabelSpan::code_range(0..code.len())],
+            filter_range: (start..start + name.len()).into(),
+            code,
         })
     }
 }